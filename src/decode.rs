@@ -0,0 +1,90 @@
+//! A stateful, resumable streaming decoder for the [REdis Serialization
+//! Protocol](https://redis.io/topics/protocol).
+//!
+//! [`parser::parse`][crate::parser::parse] is stateless: a caller reading a
+//! RESP frame off a socket in pieces has to re-run it over the whole
+//! accumulated buffer on every read, and the [`Incomplete`][nom::Err::Incomplete]
+//! need it reports is only a lower bound. [`Decoder`] keeps that bookkeeping
+//! for you. Freshly read bytes are handed to [`Decoder::feed`], and each call
+//! to [`Decoder::next`] pulls out the next fully-parsed message while retaining
+//! the unparsed tail so the following `feed` resumes without recopying.
+use crate::parser::{self, Error, OwnedType};
+use nom::Err;
+
+/// A resumable decoder that owns a growable buffer of not-yet-parsed bytes.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+}
+
+impl Decoder {
+    /// Create an empty decoder.
+    pub fn new() -> Self {
+        Decoder::default()
+    }
+
+    /// Append freshly read bytes to the decoder's buffer.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Pull the next fully-parsed message out of the buffered bytes.
+    ///
+    /// Returns `Ok(Some(_))` and drains the consumed bytes when a complete
+    /// message is available, `Ok(None)` when the buffer holds only a partial
+    /// frame (the buffer is left intact so a later [`feed`][Decoder::feed]
+    /// resumes it), and `Err(_)` when the buffered bytes are not valid RESP.
+    pub fn next(&mut self) -> Result<Option<OwnedType>, Error<Vec<u8>>> {
+        match parser::parse(&self.buf) {
+            Ok((remaining, ty)) => {
+                let consumed = self.buf.len() - remaining.len();
+                let owned = OwnedType::from(ty);
+                self.buf.drain(..consumed);
+                Ok(Some(owned))
+            }
+            Err(Err::Incomplete(_)) => Ok(None),
+            Err(Err::Error(e)) | Err(Err::Failure(e)) => Err(e.into_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type TestResult = Result<(), String>;
+
+    #[test]
+    fn decode_resumes_across_feeds() -> TestResult {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"+OK");
+        match decoder.next() {
+            Ok(None) => {}
+            other => return Err(format!("expected incomplete, not {:?}", other)),
+        }
+        decoder.feed(b"\r\n:12\r\n");
+        match decoder.next().map_err(|e| e.to_string())? {
+            Some(OwnedType::Simple(ref s)) if s == b"OK" => {}
+            other => return Err(format!("expected Simple(OK), not {:?}", other)),
+        }
+        match decoder.next().map_err(|e| e.to_string())? {
+            Some(OwnedType::Integer(12)) => {}
+            other => return Err(format!("expected Integer(12), not {:?}", other)),
+        }
+        match decoder.next().map_err(|e| e.to_string())? {
+            None => Ok(()),
+            other => Err(format!("expected nothing left, not {:?}", other)),
+        }
+    }
+
+    #[test]
+    fn decode_outlives_fed_bytes() -> TestResult {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"$5\r\nhello\r\n");
+        let msg = decoder.next().map_err(|e| e.to_string())?;
+        // The decoder's buffer is now empty, yet the message is still usable.
+        match msg {
+            Some(OwnedType::Bulk { len: 5, ref data }) if data == b"hello" => Ok(()),
+            other => Err(format!("expected Bulk(5, hello), not {:?}", other)),
+        }
+    }
+}
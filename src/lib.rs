@@ -1,5 +1,7 @@
 mod de;
+pub mod decode;
 mod error;
+pub mod parser;
 mod ser;
 
 pub use de::{from_str, Deserializer};
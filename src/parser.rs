@@ -1,11 +1,10 @@
 //! A nom-based parser for the [REdis Serialization Protocol](https://redis.io/topics/protocol).
 use nom::{
-    branch::alt,
-    bytes::streaming::{tag, take_until},
+    bytes::streaming::{tag, take, take_until},
     character::streaming::crlf,
     combinator::{map, map_res},
-    error::{ErrorKind, FromExternalError, ParseError},
-    sequence::{preceded, tuple},
+    error::{context, ContextError, ErrorKind, FromExternalError, ParseError},
+    sequence::{preceded, terminated, tuple},
     Err, IResult,
 };
 use std::fmt;
@@ -32,18 +31,97 @@ pub enum Error<I: fmt::Debug> {
         #[from]
         source: str::Utf8Error,
     },
+    /// An invalid string was encountered when parsing a [`Type::Double`]
+    #[cfg(feature = "resp3")]
+    #[error(transparent)]
+    InvalidDouble {
+        #[from]
+        source: num::ParseFloatError,
+    },
+    /// The leading byte of the input did not mark any known RESP type.
+    #[error("unknown RESP type byte: {0:?}")]
+    UnknownType(u8),
+    /// A leaf error wrapped in the stack of combinators that were running when
+    /// it occurred, innermost first. Rendered as a breadcrumb trail so a failure
+    /// buried inside a nested aggregate points at the path that reached it.
+    #[error("{}", render_context(.frames, .leaf))]
+    Context {
+        leaf: Box<Error<I>>,
+        frames: Vec<(I, &'static str)>,
+    },
     /// A generic error from nom, our parsing library.
     #[error("error {kind:?} at {input:?}")]
     Nom { kind: ErrorKind, input: I },
 }
 
+/// Render a context frame stack as `while parsing <ctx> → … → <leaf>`, with the
+/// outermost combinator first so the trail reads from the top of the message.
+fn render_context<I: fmt::Debug>(frames: &[(I, &'static str)], leaf: &Error<I>) -> String {
+    let mut trail = String::new();
+    for (_, ctx) in frames.iter().rev() {
+        trail.push_str("while parsing ");
+        trail.push_str(ctx);
+        trail.push_str(" → ");
+    }
+    trail.push_str(&leaf.to_string());
+    trail
+}
+
+impl<I: fmt::Debug> Error<I> {
+    /// Wrap this error in (or extend its) context stack with a new frame.
+    fn push_context(self, input: I, ctx: &'static str) -> Self {
+        match self {
+            Error::Context { leaf, mut frames } => {
+                frames.push((input, ctx));
+                Error::Context { leaf, frames }
+            }
+            leaf => Error::Context {
+                leaf: Box::new(leaf),
+                frames: vec![(input, ctx)],
+            },
+        }
+    }
+}
+
+impl<'a> Error<&'a [u8]> {
+    /// Copy a borrowed parse error into one that owns its input slice, so it can
+    /// be surfaced past the lifetime of the buffer it was produced from.
+    pub fn into_owned(self) -> Error<Vec<u8>> {
+        match self {
+            Error::BulkTooLarge(s) => Error::BulkTooLarge(s),
+            Error::InvalidInteger { source } => Error::InvalidInteger { source },
+            Error::InvalidStr { source } => Error::InvalidStr { source },
+            #[cfg(feature = "resp3")]
+            Error::InvalidDouble { source } => Error::InvalidDouble { source },
+            Error::UnknownType(b) => Error::UnknownType(b),
+            Error::Context { leaf, frames } => Error::Context {
+                leaf: Box::new(leaf.into_owned()),
+                frames: frames
+                    .into_iter()
+                    .map(|(input, ctx)| (input.to_vec(), ctx))
+                    .collect(),
+            },
+            Error::Nom { kind, input } => Error::Nom {
+                kind,
+                input: input.to_vec(),
+            },
+        }
+    }
+}
+
 impl<I: fmt::Debug> ParseError<I> for Error<I> {
     fn from_error_kind(input: I, kind: ErrorKind) -> Self {
         Error::Nom { input, kind }
     }
 
-    fn append(_: I, _: ErrorKind, other: Self) -> Self {
-        other
+    fn append(input: I, kind: ErrorKind, other: Self) -> Self {
+        other.push_context(input, kind.description())
+    }
+}
+
+impl<I: fmt::Debug> ContextError<I> for Error<I> {
+    fn add_context(input: I, ctx: &'static str, other: Self) -> Self {
+        other.push_context(input, ctx)
     }
 }
 
@@ -73,6 +151,102 @@ pub enum Type<'a> {
     },
     Array(Vec<Type<'a>>),
     Null,
+    /// A double-precision float (RESP3, `,` prefix).
+    #[cfg(feature = "resp3")]
+    Double(f64),
+    /// A boolean (RESP3, `#t`/`#f`).
+    #[cfg(feature = "resp3")]
+    Boolean(bool),
+    /// An arbitrary-precision integer kept verbatim (RESP3, `(` prefix).
+    #[cfg(feature = "resp3")]
+    BigNumber(&'a str),
+    /// An unordered map of field/value pairs (RESP3, `%` prefix).
+    #[cfg(feature = "resp3")]
+    Map(Vec<(Type<'a>, Type<'a>)>),
+    /// An unordered collection of elements (RESP3, `~` prefix).
+    #[cfg(feature = "resp3")]
+    Set(Vec<Type<'a>>),
+    /// A bulk string carrying a 3-byte format tag (RESP3, `=` prefix).
+    #[cfg(feature = "resp3")]
+    VerbatimString { format: [u8; 3], data: &'a [u8] },
+    /// An out-of-band push message (RESP3, `>` prefix).
+    #[cfg(feature = "resp3")]
+    Push(Vec<Type<'a>>),
+}
+
+/// An owned counterpart to [`Type`].
+///
+/// [`Type`] borrows its payloads directly from the input buffer, which is ideal
+/// for a single-shot parse but prevents a parsed message from outliving the
+/// bytes it was decoded from. The streaming [`Decoder`][crate::decode::Decoder]
+/// produces `OwnedType` values so callers can keep a message around after the
+/// consumed bytes have been drained from the decoder's buffer.
+#[derive(Debug, PartialEq, Clone)]
+pub enum OwnedType {
+    Simple(Vec<u8>),
+    Error(String),
+    Integer(i64),
+    Bulk { len: u32, data: Vec<u8> },
+    Array(Vec<OwnedType>),
+    Null,
+    #[cfg(feature = "resp3")]
+    Double(f64),
+    #[cfg(feature = "resp3")]
+    Boolean(bool),
+    #[cfg(feature = "resp3")]
+    BigNumber(String),
+    #[cfg(feature = "resp3")]
+    Map(Vec<(OwnedType, OwnedType)>),
+    #[cfg(feature = "resp3")]
+    Set(Vec<OwnedType>),
+    #[cfg(feature = "resp3")]
+    VerbatimString { format: [u8; 3], data: Vec<u8> },
+    #[cfg(feature = "resp3")]
+    Push(Vec<OwnedType>),
+}
+
+impl<'a> From<Type<'a>> for OwnedType {
+    fn from(ty: Type<'a>) -> Self {
+        match ty {
+            Type::Simple(s) => OwnedType::Simple(s.to_vec()),
+            Type::Error(s) => OwnedType::Error(s.to_owned()),
+            Type::Integer(i) => OwnedType::Integer(i),
+            Type::Bulk { len, data } => OwnedType::Bulk {
+                len,
+                data: data.to_vec(),
+            },
+            Type::Array(elems) => {
+                OwnedType::Array(elems.into_iter().map(OwnedType::from).collect())
+            }
+            Type::Null => OwnedType::Null,
+            #[cfg(feature = "resp3")]
+            Type::Double(d) => OwnedType::Double(d),
+            #[cfg(feature = "resp3")]
+            Type::Boolean(b) => OwnedType::Boolean(b),
+            #[cfg(feature = "resp3")]
+            Type::BigNumber(s) => OwnedType::BigNumber(s.to_owned()),
+            #[cfg(feature = "resp3")]
+            Type::Map(pairs) => OwnedType::Map(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (OwnedType::from(k), OwnedType::from(v)))
+                    .collect(),
+            ),
+            #[cfg(feature = "resp3")]
+            Type::Set(elems) => {
+                OwnedType::Set(elems.into_iter().map(OwnedType::from).collect())
+            }
+            #[cfg(feature = "resp3")]
+            Type::VerbatimString { format, data } => OwnedType::VerbatimString {
+                format,
+                data: data.to_vec(),
+            },
+            #[cfg(feature = "resp3")]
+            Type::Push(elems) => {
+                OwnedType::Push(elems.into_iter().map(OwnedType::from).collect())
+            }
+        }
+    }
 }
 
 fn to_str(input: &[u8]) -> StdResult<&str, Error<&u8>> {
@@ -83,6 +257,11 @@ fn to_i64(input: &str) -> StdResult<i64, Error<&str>> {
     Ok(input.parse::<i64>()?)
 }
 
+#[cfg(feature = "resp3")]
+fn to_f64(input: &str) -> StdResult<f64, Error<&str>> {
+    Ok(input.parse::<f64>()?)
+}
+
 /// Parse
 fn until_crlf(input: &[u8]) -> Result<&[u8], &[u8]> {
     let (remaining, (line, _)) = tuple((take_until("\r\n"), crlf))(input)?;
@@ -123,7 +302,9 @@ fn bulk(input: &[u8]) -> Result<&[u8], Type> {
             len, BULK_STRING_MAX
         ))))
     } else {
-        let (remaining, data) = until_crlf(remaining)?;
+        // Consume exactly `len` bytes so binary payloads containing an embedded
+        // `\r\n` survive intact, then assert and consume the trailing delimiter.
+        let (remaining, data) = terminated(take(len as usize), crlf)(remaining)?;
         Ok((
             remaining,
             Type::Bulk {
@@ -134,20 +315,181 @@ fn bulk(input: &[u8]) -> Result<&[u8], Type> {
     }
 }
 
+/// The length declared by an aggregate header.
+///
+/// RESP3 allows the usual numeric length to be replaced with a `?`, signalling
+/// that the element count is not known up front and the aggregate is terminated
+/// by a `.\r\n` marker instead.
+enum Len {
+    Fixed(i64),
+    Streamed,
+}
+
+/// Parse a `<prefix><len>\r\n` header, accepting either a number or the `?`
+/// streaming sentinel.
+fn length_line<'a>(prefix: &'a [u8]) -> impl Fn(&[u8]) -> Result<&[u8], Len> + 'a {
+    move |input: &[u8]| {
+        let (remaining, line) = prefixed_line(prefix)(input)?;
+        if line == b"?" {
+            Ok((remaining, Len::Streamed))
+        } else {
+            let n = str::from_utf8(line)
+                .ok()
+                .and_then(|s| s.parse::<i64>().ok())
+                .ok_or_else(|| Err::Error(Error::from_error_kind(input, ErrorKind::Digit)))?;
+            Ok((remaining, Len::Fixed(n)))
+        }
+    }
+}
+
+/// Collect exactly `count` sub-values.
+fn fixed_elements(mut remaining: &[u8], count: i64) -> Result<&[u8], Vec<Type>> {
+    let mut data = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        let (now_remaining, elem) = parse(remaining)?;
+        remaining = now_remaining;
+        data.push(elem);
+    }
+    Ok((remaining, data))
+}
+
+/// Collect sub-values until the `.\r\n` stream-end marker, as used by RESP3
+/// streamed aggregates declared with a `?` length.
+fn streamed_elements(mut remaining: &[u8]) -> Result<&[u8], Vec<Type>> {
+    let mut data = Vec::new();
+    loop {
+        match tuple((tag(b".".as_ref()), crlf))(remaining) {
+            Ok((after_end, _)) => {
+                remaining = after_end;
+                break;
+            }
+            // A cut stream needs more bytes before we can tell element from end.
+            Err(e @ Err::Incomplete(_)) => return Err(e),
+            Err(_) => {
+                let (now_remaining, elem) = parse(remaining)?;
+                remaining = now_remaining;
+                data.push(elem);
+            }
+        }
+    }
+    Ok((remaining, data))
+}
+
 fn array(input: &[u8]) -> Result<&[u8], Type> {
-    let (mut remaining, len) = map_res(map_res(prefixed_line(b"*"), to_str), to_i64)(input)?;
-    if len == NULL_SENTINEL {
-        Ok((remaining, Type::Null))
-    } else {
-        let mut data = Vec::with_capacity(len as usize);
-        for i in 0..len {
-            println!("reading element {}", i);
-            let (now_remaining, elem) = parse(remaining)?;
-            remaining = now_remaining;
-            data.push(elem);
+    let (remaining, len) = length_line(b"*")(input)?;
+    match len {
+        Len::Fixed(NULL_SENTINEL) => Ok((remaining, Type::Null)),
+        Len::Fixed(count) => {
+            let (remaining, data) = fixed_elements(remaining, count)?;
+            Ok((remaining, Type::Array(data)))
+        }
+        Len::Streamed => {
+            let (remaining, data) = streamed_elements(remaining)?;
+            Ok((remaining, Type::Array(data)))
+        }
+    }
+}
+
+#[cfg(feature = "resp3")]
+fn double(input: &[u8]) -> Result<&[u8], Type> {
+    map(
+        map_res(map_res(prefixed_line(b","), to_str), to_f64),
+        Type::Double,
+    )(input)
+}
+
+#[cfg(feature = "resp3")]
+fn boolean(input: &[u8]) -> Result<&[u8], Type> {
+    let (remaining, line) = prefixed_line(b"#")(input)?;
+    match line {
+        b"t" => Ok((remaining, Type::Boolean(true))),
+        b"f" => Ok((remaining, Type::Boolean(false))),
+        _ => Err(Err::Error(Error::from_error_kind(input, ErrorKind::Tag))),
+    }
+}
+
+#[cfg(feature = "resp3")]
+fn big_number(input: &[u8]) -> Result<&[u8], Type> {
+    map(map_res(prefixed_line(b"("), to_str), Type::BigNumber)(input)
+}
+
+#[cfg(feature = "resp3")]
+fn resp_map(input: &[u8]) -> Result<&[u8], Type> {
+    let (remaining, len) = length_line(b"%")(input)?;
+    let (remaining, pairs) = match len {
+        // Each pair is two sub-values, so a map of `n` pairs is `2 * n` elements.
+        Len::Fixed(count) => {
+            let (remaining, flat) = fixed_elements(remaining, count.saturating_mul(2))?;
+            (remaining, pair_up(flat))
+        }
+        Len::Streamed => {
+            let (remaining, flat) = streamed_elements(remaining)?;
+            (remaining, pair_up(flat))
         }
-        Ok((remaining, Type::Array(data)))
+    };
+    Ok((remaining, Type::Map(pairs)))
+}
+
+/// Fold a flat element list into field/value pairs, dropping a dangling key.
+#[cfg(feature = "resp3")]
+fn pair_up(flat: Vec<Type>) -> Vec<(Type, Type)> {
+    let mut pairs = Vec::with_capacity(flat.len() / 2);
+    let mut it = flat.into_iter();
+    while let (Some(key), Some(value)) = (it.next(), it.next()) {
+        pairs.push((key, value));
     }
+    pairs
+}
+
+#[cfg(feature = "resp3")]
+fn set(input: &[u8]) -> Result<&[u8], Type> {
+    let (remaining, elems) = aggregate(b"~", input)?;
+    Ok((remaining, Type::Set(elems)))
+}
+
+#[cfg(feature = "resp3")]
+fn push(input: &[u8]) -> Result<&[u8], Type> {
+    let (remaining, elems) = aggregate(b">", input)?;
+    Ok((remaining, Type::Push(elems)))
+}
+
+/// Parse a `<prefix><len>\r\n` header followed by its sub-values, supporting
+/// both a fixed count and the `?` streaming sentinel.
+#[cfg(feature = "resp3")]
+fn aggregate<'a>(prefix: &'static [u8], input: &'a [u8]) -> Result<&'a [u8], Vec<Type<'a>>> {
+    let (remaining, len) = length_line(prefix)(input)?;
+    match len {
+        Len::Fixed(count) => fixed_elements(remaining, count),
+        Len::Streamed => streamed_elements(remaining),
+    }
+}
+
+#[cfg(feature = "resp3")]
+fn verbatim(input: &[u8]) -> Result<&[u8], Type> {
+    let (remaining, len) = map_res(map_res(prefixed_line(b"="), to_str), to_i64)(input)?;
+    if len > BULK_STRING_MAX {
+        return Err(Err::Error(Error::BulkTooLarge(format!(
+            "length of {} is greater than the max of {}",
+            len, BULK_STRING_MAX
+        ))));
+    }
+    let (remaining, payload) = terminated(take(len as usize), crlf)(remaining)?;
+    // The payload is a 3-byte format tag, a `:` separator, then the data.
+    if payload.len() < 4 || payload[3] != b':' {
+        return Err(Err::Error(Error::from_error_kind(input, ErrorKind::Tag)));
+    }
+    Ok((
+        remaining,
+        Type::VerbatimString {
+            format: [payload[0], payload[1], payload[2]],
+            data: &payload[4..],
+        },
+    ))
+}
+
+#[cfg(feature = "resp3")]
+fn null(input: &[u8]) -> Result<&[u8], Type> {
+    map(tuple((tag(b"_".as_ref()), crlf)), |_| Type::Null)(input)
 }
 
 /// Attempt to parse an RESP [`Type`][Type] from the provided buffer.
@@ -185,7 +527,33 @@ fn array(input: &[u8]) -> Result<&[u8], Type> {
 /// assert!(result.is_err());
 /// ```
 pub fn parse(input: &[u8]) -> Result<&[u8], Type> {
-    alt((simple_str, error, integer, bulk, array))(input)
+    // Peek the discriminant byte and dispatch straight to the right combinator,
+    // rather than running each alternative's `tag` in turn until one matches.
+    match input.first() {
+        None => Err(Err::Incomplete(nom::Needed::new(1))),
+        Some(b'+') => context("simple string", simple_str)(input),
+        Some(b'-') => context("error", error)(input),
+        Some(b':') => context("integer", integer)(input),
+        Some(b'$') => context("bulk string", bulk)(input),
+        Some(b'*') => context("array", array)(input),
+        #[cfg(feature = "resp3")]
+        Some(b',') => context("double", double)(input),
+        #[cfg(feature = "resp3")]
+        Some(b'#') => context("boolean", boolean)(input),
+        #[cfg(feature = "resp3")]
+        Some(b'(') => context("big number", big_number)(input),
+        #[cfg(feature = "resp3")]
+        Some(b'%') => context("map", resp_map)(input),
+        #[cfg(feature = "resp3")]
+        Some(b'~') => context("set", set)(input),
+        #[cfg(feature = "resp3")]
+        Some(b'=') => context("verbatim string", verbatim)(input),
+        #[cfg(feature = "resp3")]
+        Some(b'>') => context("push", push)(input),
+        #[cfg(feature = "resp3")]
+        Some(b'_') => context("null", null)(input),
+        Some(&b) => Err(Err::Error(Error::UnknownType(b))),
+    }
 }
 
 #[cfg(test)]
@@ -249,6 +617,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_bulk_binary_safe() -> TestResult {
+        let (_, parsed) = bulk(b"$6\r\nhe\r\nlo\r\n").map_err(|e| e.to_string())?;
+        match parsed {
+            Type::Bulk {
+                len: 6,
+                data: b"he\r\nlo",
+            } => Ok(()),
+            _ => Err(format!("expected Bulk(6, he\\r\\nlo), not {:?}", parsed)),
+        }
+    }
+
     #[test]
     fn parse_bulk_null() -> TestResult {
         let (_, parsed) = bulk(b"$-1\r\n").map_err(|e| e.to_string())?;
@@ -331,6 +711,118 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_unknown_type_byte() -> TestResult {
+        match parse(b"@nope\r\n") {
+            Err(nom::Err::Error(Error::UnknownType(b'@'))) => Ok(()),
+            other => Err(format!("expected UnknownType('@'), not {:?}", other)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_double_ok() -> TestResult {
+        match parse(b",3.14\r\n").map_err(|e| e.to_string())? {
+            (_, Type::Double(d)) if (d - 3.14).abs() < f64::EPSILON => Ok(()),
+            (_, parsed) => Err(format!("expected Double(3.14), not {:?}", parsed)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_boolean_ok() -> TestResult {
+        match parse(b"#t\r\n").map_err(|e| e.to_string())? {
+            (_, Type::Boolean(true)) => Ok(()),
+            (_, parsed) => Err(format!("expected Boolean(true), not {:?}", parsed)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_big_number_ok() -> TestResult {
+        match parse(b"(3492890328409238509324850943850943825024385\r\n").map_err(|e| e.to_string())?
+        {
+            (_, Type::BigNumber("3492890328409238509324850943850943825024385")) => Ok(()),
+            (_, parsed) => Err(format!("expected BigNumber, not {:?}", parsed)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_map_ok() -> TestResult {
+        match parse(b"%1\r\n+first\r\n:1\r\n").map_err(|e| e.to_string())? {
+            (_, Type::Map(pairs))
+                if pairs == vec![(Type::Simple(b"first"), Type::Integer(1))] =>
+            {
+                Ok(())
+            }
+            (_, parsed) => Err(format!("expected Map, not {:?}", parsed)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_set_ok() -> TestResult {
+        match parse(b"~2\r\n+OK\r\n:12\r\n").map_err(|e| e.to_string())? {
+            (_, Type::Set(elems))
+                if elems == vec![Type::Simple(b"OK"), Type::Integer(12)] =>
+            {
+                Ok(())
+            }
+            (_, parsed) => Err(format!("expected Set, not {:?}", parsed)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_verbatim_ok() -> TestResult {
+        match parse(b"=15\r\ntxt:Some string\r\n").map_err(|e| e.to_string())? {
+            (
+                _,
+                Type::VerbatimString {
+                    format: [b't', b'x', b't'],
+                    data: b"Some string",
+                },
+            ) => Ok(()),
+            (_, parsed) => Err(format!("expected VerbatimString, not {:?}", parsed)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_push_ok() -> TestResult {
+        match parse(b">1\r\n+pubsub\r\n").map_err(|e| e.to_string())? {
+            (_, Type::Push(elems)) if elems == vec![Type::Simple(b"pubsub")] => Ok(()),
+            (_, parsed) => Err(format!("expected Push, not {:?}", parsed)),
+        }
+    }
+
+    #[cfg(feature = "resp3")]
+    #[test]
+    fn parse_null_ok() -> TestResult {
+        match parse(b"_\r\n").map_err(|e| e.to_string())? {
+            (_, Type::Null) => Ok(()),
+            (_, parsed) => Err(format!("expected Null, not {:?}", parsed)),
+        }
+    }
+
+    #[test]
+    fn parse_context_trail() -> TestResult {
+        // A malformed integer buried in an array should surface the path that
+        // reached it, not just the opaque leaf error.
+        match parse(b"*1\r\n:nope\r\n") {
+            Err(nom::Err::Error(e)) => {
+                let msg = e.to_string();
+                if msg.contains("array") && msg.contains("integer") {
+                    Ok(())
+                } else {
+                    Err(format!("expected an array → integer trail, got {:?}", msg))
+                }
+            }
+            other => Err(format!("expected a context error, not {:?}", other)),
+        }
+    }
+
     #[test]
     fn parse_incomplete() -> TestResult {
         let result = parse(b"+OK");